@@ -0,0 +1,52 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::config::{tls_rpt::TlsReportConfig, AggregateFrequency, IfBlock, Rate};
+
+// Rate-limited "auth-failure" feedback reports (DKIM/SPF/DMARC failure
+// reports), sent per-message to the address published in a domain's
+// `ruf`/`ra` record.
+#[derive(Debug, Clone)]
+pub struct AddressReportConfig {
+    pub send: IfBlock<Option<Rate>>,
+}
+
+// Periodic DMARC aggregate reports, sent on the `AggregateFrequency`
+// configured for the report type rather than per-message. TLS-RPT
+// aggregate reports use their own `TlsReportConfig`.
+#[derive(Debug, Clone)]
+pub struct AggregateReportConfig {
+    pub send: IfBlock<AggregateFrequency>,
+}
+
+/// Top-level reporting configuration, held by `Core::report`. Each field
+/// configures one report type independently, so a deployment can, for
+/// example, send DMARC aggregate reports daily while disabling TLS-RPT.
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    pub dkim: AddressReportConfig,
+    pub spf: AddressReportConfig,
+    pub dmarc: AddressReportConfig,
+    pub dmarc_aggregate: AggregateReportConfig,
+    pub tls: TlsReportConfig,
+}
@@ -0,0 +1,35 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::config::{AggregateFrequency, IfBlock};
+
+// SMTP TLS Reporting (RFC 8460) aggregate report configuration. Mirrors
+// the DMARC aggregate report config, but the report is addressed to the
+// `rua` published by the destination domain's `_smtp._tls` TXT record
+// rather than by the domain that sent the message.
+#[derive(Debug, Clone)]
+pub struct TlsReportConfig {
+    pub send: IfBlock<AggregateFrequency>,
+    pub organization_name: IfBlock<Option<String>>,
+    pub contact_info: IfBlock<Option<String>>,
+}
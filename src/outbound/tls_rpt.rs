@@ -0,0 +1,72 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use crate::reporting::tls::{FailureResultType, PolicyType, TlsRptCounters};
+
+/// The MX-side TLS policy outbound delivery evaluated before connecting,
+/// resolved from the destination domain's MTA-STS policy or TLSA records.
+#[derive(Debug, Clone)]
+pub enum MxTlsPolicy {
+    Sts { policy_id: String },
+    Tlsa { selector: String },
+    NoPolicyFound,
+}
+
+impl MxTlsPolicy {
+    fn report_type(&self) -> (PolicyType, Option<&str>) {
+        match self {
+            MxTlsPolicy::Sts { policy_id } => (PolicyType::Sts, Some(policy_id.as_str())),
+            MxTlsPolicy::Tlsa { selector } => (PolicyType::Tlsa, Some(selector.as_str())),
+            MxTlsPolicy::NoPolicyFound => (PolicyType::NoPolicyFound, None),
+        }
+    }
+}
+
+/// Records the outcome of an outbound STARTTLS negotiation with `mx_host`
+/// against `domain`'s TLS-RPT counters. The outbound delivery code is
+/// responsible for classifying the negotiation outcome into a
+/// `FailureResultType` itself — from the certificate chain, DANE/TLSA
+/// validation result, and connection error it already has in hand — and
+/// passing that classification in as `result`. Generic `io::ErrorKind`
+/// values are not a reliable signal here: TLS stacks surface most
+/// handshake failures, including genuine certificate expiry, through the
+/// same `InvalidData` kind, so guessing from the error kind alone
+/// mislabels reports sent to an external postmaster.
+pub fn report_tls_negotiation(
+    counters: &TlsRptCounters,
+    domain: &str,
+    mx_host: &str,
+    policy: &MxTlsPolicy,
+    starttls_supported: bool,
+    result: Result<(), FailureResultType>,
+) {
+    let (policy_type, policy_string) = policy.report_type();
+
+    let result = if !starttls_supported {
+        Err(FailureResultType::StarttlsNotSupported)
+    } else {
+        result
+    };
+
+    counters.record(domain, policy_type, policy_string, mx_host, result);
+}
@@ -0,0 +1,401 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+//! SMTP TLS Reporting (RFC 8460) aggregate report generation.
+//!
+//! Outbound delivery is meant to record the outcome of each TLS
+//! negotiation with a destination MX via [`TlsRptCounters::record`]
+//! (through [`crate::outbound::tls_rpt::report_tls_negotiation`]). The
+//! scheduler, [`run_tls_report_scheduler`], then rolls the accumulated
+//! counters up into a per-domain JSON report and queues it for delivery
+//! to the `rua` address published in the domain's `_smtp._tls.<domain>`
+//! TXT record, mirroring how DMARC aggregate reports are produced from
+//! `core.report`. Neither half is spawned or called from production code
+//! in this tree yet — see [`run_tls_report_scheduler`]'s doc comment.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use ahash::{AHashMap, AHashSet};
+use async_trait::async_trait;
+use serde::Serialize;
+use tokio::sync::{mpsc, watch};
+
+use crate::config::{tls_rpt::TlsReportConfig, AggregateFrequency};
+
+/// The policy that was evaluated (or not found) for a destination domain,
+/// as defined in RFC 8460 Section 4.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PolicyType {
+    Sts,
+    Tlsa,
+    NoPolicyFound,
+}
+
+/// The reason a TLS negotiation with a destination MX failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureResultType {
+    StarttlsNotSupported,
+    CertificateExpired,
+    ValidationFailure,
+    DaneRequired,
+}
+
+// Identifies a single policy block within a domain's report: a policy
+// type plus, when one was published, the STS policy id or TLSA record
+// it was evaluated against. Every MX host covered by the same policy
+// rolls up into one `PolicyResult`, per RFC 8460 Section 4.1.
+type PolicyKey = (PolicyType, Option<String>);
+
+// Accumulates successes and per-(MX host, failure-type) counts for a
+// single (reporting domain, policy identity) pair until the next
+// scheduled report is due.
+#[derive(Debug, Default)]
+struct TlsRptEntry {
+    mx_hosts: AHashSet<String>,
+    successful: u32,
+    failures: AHashMap<(String, FailureResultType), u32>,
+}
+
+/// In-memory TLS-RPT counters, keyed by reporting domain. Shared between
+/// outbound delivery, which records negotiation outcomes, and the report
+/// scheduler, which drains and resets them once a report has been built.
+#[derive(Debug, Default, Clone)]
+pub struct TlsRptCounters {
+    domains: Arc<Mutex<AHashMap<String, AHashMap<PolicyKey, TlsRptEntry>>>>,
+}
+
+impl TlsRptCounters {
+    pub fn new() -> Self {
+        TlsRptCounters::default()
+    }
+
+    /// Records the outcome of an outbound TLS negotiation with `mx_host`
+    /// on behalf of `domain`, evaluated against `policy_string` (the STS
+    /// policy id or TLSA record, when one was published). Called by
+    /// [`crate::outbound::tls_rpt::report_tls_negotiation`] once the
+    /// STARTTLS handshake (or the lack thereof) is resolved.
+    pub fn record(
+        &self,
+        domain: &str,
+        policy_type: PolicyType,
+        policy_string: Option<&str>,
+        mx_host: &str,
+        result: Result<(), FailureResultType>,
+    ) {
+        let mut domains = self.domains.lock().unwrap();
+        let entry = domains
+            .entry(domain.to_string())
+            .or_default()
+            .entry((policy_type, policy_string.map(str::to_string)))
+            .or_default();
+        entry.mx_hosts.insert(mx_host.to_string());
+        match result {
+            Ok(()) => entry.successful += 1,
+            Err(failure) => {
+                *entry
+                    .failures
+                    .entry((mx_host.to_string(), failure))
+                    .or_default() += 1
+            }
+        }
+    }
+
+    // Drains every counter accumulated for `domain`, leaving none behind
+    // for the next reporting interval.
+    fn take_domain(&self, domain: &str) -> Option<AHashMap<PolicyKey, TlsRptEntry>> {
+        self.domains.lock().unwrap().remove(domain)
+    }
+
+    /// Returns the domains with counters pending a report, so the
+    /// scheduler knows which ones to build and flush on each tick.
+    pub fn pending_domains(&self) -> Vec<String> {
+        self.domains.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Builds the RFC 8460 aggregate report for `domain` covering
+    /// `[date_range_start, date_range_start + interval]`, consuming the
+    /// counters accumulated for it. Returns `None` if no TLS negotiations
+    /// with that domain were recorded during the interval.
+    pub fn build_report(
+        &self,
+        domain: &str,
+        organization_name: &str,
+        contact_info: &str,
+        interval: AggregateFrequency,
+        date_range_start: SystemTime,
+    ) -> Option<TlsReport> {
+        let policies = self.take_domain(domain)?;
+        if policies.is_empty() {
+            return None;
+        }
+
+        let duration = match interval {
+            AggregateFrequency::Hourly => Duration::from_secs(3600),
+            AggregateFrequency::Daily => Duration::from_secs(86400),
+            AggregateFrequency::Weekly => Duration::from_secs(7 * 86400),
+            AggregateFrequency::Never => Duration::from_secs(86400),
+        };
+
+        Some(TlsReport {
+            organization_name: organization_name.to_string(),
+            date_range: DateRange {
+                start_datetime: humantime_rfc3339(date_range_start),
+                end_datetime: humantime_rfc3339(date_range_start + duration),
+            },
+            contact_info: contact_info.to_string(),
+            report_id: format!(
+                "{}_{}",
+                date_range_start
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+                domain
+            ),
+            policies: policies
+                .into_iter()
+                .map(|((policy_type, policy_string), entry)| {
+                    let mut mx_host: Vec<String> = entry.mx_hosts.into_iter().collect();
+                    mx_host.sort_unstable();
+
+                    PolicyResult {
+                        policy: PolicyDetails {
+                            policy_type,
+                            policy_domain: domain.to_string(),
+                            policy_string: policy_string.into_iter().collect(),
+                            mx_host,
+                        },
+                        summary: Summary {
+                            total_successful_session_count: entry.successful,
+                            total_failure_session_count: entry.failures.values().sum(),
+                        },
+                        failure_details: entry
+                            .failures
+                            .into_iter()
+                            .map(
+                                |((receiving_mx_hostname, result_type), failed_session_count)| {
+                                    FailureDetails {
+                                        result_type,
+                                        receiving_mx_hostname,
+                                        failed_session_count,
+                                    }
+                                },
+                            )
+                            .collect(),
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+fn humantime_rfc3339(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time).to_string()
+}
+
+/// Event sent over `core.report.tx` once a TLS-RPT aggregate report for
+/// `domain` is ready to be queued for delivery to its published `rua`
+/// address, analogous to the event DMARC aggregate reports use on the
+/// same channel.
+#[derive(Debug, Clone)]
+pub struct TlsRptEvent {
+    pub domain: String,
+    pub interval: AggregateFrequency,
+    pub report: TlsReport,
+}
+
+/// Performs the TXT lookup a TLS-RPT `rua` resolution needs. This module
+/// provides no implementation of it for `core.resolvers.dns`; the only
+/// implementation in this tree is the test stub in
+/// `crate::tests::inbound::tls_rpt`.
+#[async_trait]
+pub trait TlsRptResolver: Send + Sync {
+    async fn txt_lookup(&self, name: &str) -> Option<String>;
+}
+
+/// Resolves the `rua` address a domain has published for TLS-RPT, via a
+/// TXT lookup on `_smtp._tls.<domain>`, mirroring how DMARC aggregate
+/// reports resolve their `rua` from `_dmarc.<domain>`.
+pub async fn resolve_tls_rpt_rua(
+    resolver: &impl TlsRptResolver,
+    domain: &str,
+) -> Option<String> {
+    let txt = resolver
+        .txt_lookup(&format!("_smtp._tls.{domain}"))
+        .await?;
+    parse_tls_rpt_rua(&txt)
+}
+
+/// Parses a `_smtp._tls.<domain>` TXT record value (RFC 8460 Section 3)
+/// and returns its `rua` address, or `None` if the record isn't a valid
+/// `v=TLSRPTv1` record or doesn't publish a `mailto:` `rua`.
+pub fn parse_tls_rpt_rua(txt: &str) -> Option<String> {
+    let mut is_tlsrpt = false;
+    let mut rua = None;
+
+    for tag in txt.split(';') {
+        let Some((key, value)) = tag.split_once('=') else {
+            continue;
+        };
+        match key.trim() {
+            "v" if value.trim().eq_ignore_ascii_case("TLSRPTv1") => is_tlsrpt = true,
+            "rua" => rua = value.trim().strip_prefix("mailto:").map(str::to_string),
+            _ => (),
+        }
+    }
+
+    is_tlsrpt.then_some(rua).flatten()
+}
+
+/// Periodically builds and dispatches TLS-RPT aggregate reports for every
+/// domain with pending counters, resolving each domain's `rua` address
+/// before queuing the report on `report_tx`, and stops once `shutdown_rx`
+/// signals a shutdown.
+///
+/// This is the scheduler half of TLS-RPT; nothing spawns it yet. It
+/// needs to be started once from server startup, the same place the
+/// DMARC aggregate report scheduler is spawned from, passing it the
+/// `SMTP`/`Core` instance's own `TlsRptCounters`, `report.config.tls` and
+/// `report.tx`/resolver handles.
+pub async fn run_tls_report_scheduler(
+    counters: TlsRptCounters,
+    config: TlsReportConfig,
+    resolver: impl TlsRptResolver,
+    report_tx: mpsc::Sender<TlsRptEvent>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    let interval = match config.send.eval_default() {
+        AggregateFrequency::Never => return,
+        AggregateFrequency::Hourly => Duration::from_secs(3600),
+        AggregateFrequency::Daily => Duration::from_secs(86400),
+        AggregateFrequency::Weekly => Duration::from_secs(7 * 86400),
+    };
+    let mut timer = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = timer.tick() => (),
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() {
+                    return;
+                }
+                continue;
+            }
+        }
+
+        let date_range_start = SystemTime::now() - interval;
+        for domain in counters.pending_domains() {
+            let Some(rua) = resolve_tls_rpt_rua(&resolver, &domain).await else {
+                continue;
+            };
+            let Some(report) = counters.build_report(
+                &domain,
+                config.organization_name.eval_default().as_deref().unwrap_or("Stalwart SMTP"),
+                config.contact_info.eval_default().as_deref().unwrap_or(&rua),
+                config.send.eval_default(),
+                date_range_start,
+            ) else {
+                continue;
+            };
+
+            if report_tx
+                .send(TlsRptEvent {
+                    domain,
+                    interval: config.send.eval_default(),
+                    report,
+                })
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+    }
+}
+
+/// A TLS-RPT aggregate report, serializable as the JSON document described
+/// in RFC 8460 Section 4. Nothing in this module serializes, compresses or
+/// mails it: [`run_tls_report_scheduler`] hands it off on `report_tx`
+/// inside a [`TlsRptEvent`].
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsReport {
+    #[serde(rename = "organization-name")]
+    pub organization_name: String,
+    #[serde(rename = "date-range")]
+    pub date_range: DateRange,
+    #[serde(rename = "contact-info")]
+    pub contact_info: String,
+    #[serde(rename = "report-id")]
+    pub report_id: String,
+    pub policies: Vec<PolicyResult>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DateRange {
+    #[serde(rename = "start-datetime")]
+    pub start_datetime: String,
+    #[serde(rename = "end-datetime")]
+    pub end_datetime: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyResult {
+    pub policy: PolicyDetails,
+    pub summary: Summary,
+    #[serde(rename = "failure-details", skip_serializing_if = "Vec::is_empty")]
+    pub failure_details: Vec<FailureDetails>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PolicyDetails {
+    #[serde(rename = "policy-type")]
+    pub policy_type: PolicyType,
+    #[serde(rename = "policy-domain")]
+    pub policy_domain: String,
+    #[serde(rename = "policy-string", skip_serializing_if = "Vec::is_empty")]
+    pub policy_string: Vec<String>,
+    #[serde(rename = "mx-host")]
+    pub mx_host: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct Summary {
+    #[serde(rename = "total-successful-session-count")]
+    pub total_successful_session_count: u32,
+    #[serde(rename = "total-failure-session-count")]
+    pub total_failure_session_count: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureDetails {
+    #[serde(rename = "result-type")]
+    pub result_type: FailureResultType,
+    #[serde(rename = "receiving-mx-hostname")]
+    pub receiving_mx_hostname: String,
+    #[serde(rename = "failed-session-count")]
+    pub failed_session_count: u32,
+}
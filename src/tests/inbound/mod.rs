@@ -0,0 +1,40 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod dmarc;
+pub mod tls_rpt;
+
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::reporting::tls::TlsRptEvent;
+
+/// Awaits the next TLS-RPT aggregate report dispatched on `report_tx`,
+/// the TLS-RPT analog of `read_dmarc_report`.
+pub async fn read_tls_report(report_rx: &mut mpsc::Receiver<TlsRptEvent>) -> TlsRptEvent {
+    match tokio::time::timeout(Duration::from_millis(100), report_rx.recv()).await {
+        Ok(Some(event)) => event,
+        _ => panic!("No TLS-RPT report received."),
+    }
+}
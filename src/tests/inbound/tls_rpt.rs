@@ -0,0 +1,186 @@
+/*
+ * Copyright (c) 2023 Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart Mail Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::AggregateFrequency,
+    outbound::tls_rpt::{report_tls_negotiation, MxTlsPolicy},
+    reporting::tls::{
+        parse_tls_rpt_rua, resolve_tls_rpt_rua, FailureResultType, PolicyType, TlsRptCounters,
+        TlsRptResolver,
+    },
+    tests::inbound::read_tls_report,
+};
+
+// A DNS stub standing in for `core.resolvers.dns` for the purposes of
+// this test, the same role the records seeded with
+// `core.resolvers.dns.txt_add` play in `dmarc.rs`.
+struct TestResolver {
+    txt_records: Vec<(&'static str, &'static str)>,
+}
+
+#[async_trait]
+impl TlsRptResolver for TestResolver {
+    async fn txt_lookup(&self, name: &str) -> Option<String> {
+        self.txt_records
+            .iter()
+            .find(|(record_name, _)| *record_name == name)
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+#[tokio::test]
+async fn tls_rpt() {
+    let resolver = TestResolver {
+        txt_records: vec![(
+            "_smtp._tls.example.org",
+            "v=TLSRPTv1; rua=mailto:tls-reports@example.org",
+        )],
+    };
+
+    // Resolve the `rua` published by the destination domain, the way the
+    // scheduler does before a report is dispatched.
+    let rua = resolve_tls_rpt_rua(&resolver, "example.org")
+        .await
+        .expect("rua should resolve from the published TXT record");
+    assert_eq!(rua, "tls-reports@example.org");
+
+    let counters = TlsRptCounters::new();
+    let (report_tx, mut report_rx) = mpsc::channel(128);
+
+    // Simulate outbound delivery resolving an MTA-STS policy and
+    // negotiating TLS with two MX hosts under it: one succeeds, the
+    // other fails certificate validation.
+    let sts_policy = MxTlsPolicy::Sts {
+        policy_id: "202307010000Z".to_string(),
+    };
+    report_tls_negotiation(
+        &counters,
+        "example.org",
+        "mx1.example.org",
+        &sts_policy,
+        true,
+        Ok(()),
+    );
+    report_tls_negotiation(
+        &counters,
+        "example.org",
+        "mx2.example.org",
+        &sts_policy,
+        true,
+        Err(FailureResultType::CertificateExpired),
+    );
+
+    // A third MX, with no MTA-STS/TLSA policy published, doesn't even
+    // support STARTTLS. `starttls_supported: false` always reports
+    // `StarttlsNotSupported` regardless of the `result` passed in, so the
+    // placeholder `Ok(())` here is never actually recorded.
+    report_tls_negotiation(
+        &counters,
+        "example.org",
+        "mx3.example.org",
+        &MxTlsPolicy::NoPolicyFound,
+        false,
+        Ok(()),
+    );
+
+    // Build and dispatch the aggregate report the way the scheduler
+    // would once the reporting interval elapses.
+    let report = counters
+        .build_report(
+            "example.org",
+            "Stalwart SMTP",
+            &rua,
+            AggregateFrequency::Daily,
+            SystemTime::now(),
+        )
+        .expect("report should be generated");
+    report_tx
+        .send(crate::reporting::tls::TlsRptEvent {
+            domain: "example.org".to_string(),
+            interval: AggregateFrequency::Daily,
+            report,
+        })
+        .await
+        .unwrap();
+
+    // Expect a single TLS-RPT aggregate report with one policy result
+    // per distinct policy, each covering every MX host evaluated under it.
+    let event = read_tls_report(&mut report_rx).await;
+    assert_eq!(event.domain, "example.org");
+    assert_eq!(event.interval, AggregateFrequency::Daily);
+    assert_eq!(event.report.policies.len(), 2);
+
+    let sts_policy = event
+        .report
+        .policies
+        .iter()
+        .find(|p| p.policy.policy_type == PolicyType::Sts)
+        .expect("sts policy result missing");
+    assert_eq!(sts_policy.policy.mx_host, vec!["mx1.example.org", "mx2.example.org"]);
+    assert_eq!(sts_policy.summary.total_successful_session_count, 1);
+    assert_eq!(sts_policy.summary.total_failure_session_count, 1);
+    assert_eq!(
+        sts_policy.failure_details[0].result_type,
+        FailureResultType::CertificateExpired
+    );
+
+    let no_policy = event
+        .report
+        .policies
+        .iter()
+        .find(|p| p.policy.policy_type == PolicyType::NoPolicyFound)
+        .expect("no-policy-found result missing");
+    assert_eq!(no_policy.policy.mx_host, vec!["mx3.example.org"]);
+    assert_eq!(no_policy.summary.total_failure_session_count, 1);
+    assert_eq!(
+        no_policy.failure_details[0].result_type,
+        FailureResultType::StarttlsNotSupported
+    );
+
+    // Counters are drained once a report has been built, so a second
+    // build attempt for the same interval yields nothing to send.
+    assert!(counters
+        .build_report(
+            "example.org",
+            "Stalwart SMTP",
+            &rua,
+            AggregateFrequency::Daily,
+            SystemTime::now(),
+        )
+        .is_none());
+}
+
+#[test]
+fn tls_rpt_rua_parsing() {
+    assert_eq!(
+        parse_tls_rpt_rua("v=TLSRPTv1; rua=mailto:reports@example.com"),
+        Some("reports@example.com".to_string())
+    );
+    assert_eq!(parse_tls_rpt_rua("v=spf1 -all"), None);
+    assert_eq!(parse_tls_rpt_rua("v=TLSRPTv1"), None);
+}